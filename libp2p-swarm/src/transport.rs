@@ -34,8 +34,11 @@ use futures::{Stream, Poll, Async};
 use futures::future::{IntoFuture, Future, ok as future_ok, FutureResult};
 use multiaddr::Multiaddr;
 use multistream_select;
-use std::io::{Cursor, Error as IoError, Read, Write};
+use std::error;
+use std::fmt;
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind, Read, Write};
 use std::iter;
+use std::sync::Arc;
 use tokio_io::{AsyncRead, AsyncWrite};
 
 /// A transport is an object that can be used to produce connections by listening or dialing a
@@ -58,16 +61,27 @@ pub trait Transport {
 	/// A future which indicates that we are currently dialing to a peer.
 	type Dial: IntoFuture<Item = Self::RawConn, Error = IoError>;
 
+	/// Error that can happen when dialing or listening.
+	type Error: error::Error;
+
 	/// Listen on the given multi-addr.
 	///
-	/// Returns the address back if it isn't supported.
-	fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, (Self, Multiaddr)>
+	/// Returns the listener together with the address it is effectively bound to, which may
+	/// differ from the requested one (for example when listening on `/ip4/0.0.0.0/tcp/0` the
+	/// returned address carries the interface and the OS-assigned port).
+	///
+	/// Returns `TransportError::MultiaddrNotSupported` if this transport doesn't support the
+	/// requested address.
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
 		where Self: Sized;
 
 	/// Dial to the given multi-addr.
 	///
-	/// Returns either a future which may resolve to a connection, or gives back the multiaddress.
-	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, (Self, Multiaddr)> where Self: Sized;
+	/// Returns a future which may resolve to a connection, or
+	/// `TransportError::MultiaddrNotSupported` if this transport doesn't support the address.
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>>
+		where Self: Sized;
 
 	/// Builds a new struct that implements `Transport` that contains both `self` and `other`.
 	///
@@ -95,6 +109,162 @@ pub trait Transport {
 			upgrade: upgrade,
 		}
 	}
+
+	/// Applies a function on the connections created by the transport.
+	///
+	/// The function is applied to the connection produced both when dialing and when listening.
+	#[inline]
+	fn map<F, O>(self, map: F) -> Map<Self, F>
+		where Self: Sized,
+			  F: FnOnce(Self::RawConn) -> O + Clone,
+			  O: AsyncRead + AsyncWrite
+	{
+		Map {
+			transport: self,
+			map: map,
+		}
+	}
+
+	/// Applies a function on the errors produced by `listen_on` and `dial`.
+	#[inline]
+	fn map_err<F, E>(self, map_err: F) -> MapErr<Self, F>
+		where Self: Sized,
+			  F: FnOnce(Self::Error) -> E + Clone,
+			  E: error::Error
+	{
+		MapErr {
+			transport: self,
+			map_err: map_err,
+		}
+	}
+
+	/// Adds an asynchronous step applied to a connection after it has been established.
+	///
+	/// The function is called with the connection and returns a future that resolves to the new
+	/// connection, both when dialing and when listening.
+	#[inline]
+	fn and_then<C, F, O>(self, then: C) -> AndThen<Self, C>
+		where Self: Sized,
+			  C: FnOnce(Self::RawConn) -> F + Clone,
+			  F: IntoFuture<Item = O, Error = IoError>,
+			  O: AsyncRead + AsyncWrite
+	{
+		AndThen {
+			transport: self,
+			fun: then,
+		}
+	}
+
+	/// Turns this transport into a `Boxed` transport, erasing all of its nested generic types
+	/// behind boxed trait objects for the listener stream and the dial future.
+	///
+	/// This is handy to store a configured transport in a struct field or to return it from a
+	/// function without spelling out its (often deeply nested) concrete type.
+	#[inline]
+	fn boxed(self) -> Boxed<Self::RawConn, Self::Error>
+		where Self: Sized + Clone + Send + Sync + 'static,
+			  Self::Listener: Send + 'static,
+			  Self::Dial: 'static,
+			  <Self::Dial as IntoFuture>::Future: Send + 'static
+	{
+		Boxed {
+			inner: Arc::new(self),
+		}
+	}
+
+	/// Computes the address through which we can be publicly reached, given the address we believe
+	/// we listen on (`server`) and the address a remote peer reports having observed the connection
+	/// coming from (`observed`).
+	///
+	/// The result substitutes the observed IP/port layers into `server` while keeping the
+	/// protocol-specific suffixes of `server`, or `None` if the address can't be computed (for
+	/// example if the transport doesn't understand `observed`).
+	#[inline]
+	fn nat_traversal(&self, _server: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+		None
+	}
+}
+
+/// Error that can happen when listening or dialing with a `Transport`.
+#[derive(Debug, Clone)]
+pub enum TransportError<TErr> {
+	/// The multiaddress is not supported by this transport.
+	///
+	/// The address is given back so that a different transport can be tried instead.
+	MultiaddrNotSupported(Multiaddr),
+	/// Any other error that the transport may produce.
+	Other(TErr),
+}
+
+impl<TErr> fmt::Display for TransportError<TErr>
+	where TErr: fmt::Display
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			TransportError::MultiaddrNotSupported(ref addr) => {
+				write!(f, "multiaddress {} is not supported", addr)
+			}
+			TransportError::Other(ref err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl<TErr> error::Error for TransportError<TErr>
+	where TErr: error::Error
+{
+}
+
+/// Error produced by the `Transport` returned by `or_transport`.
+///
+/// Combines the heterogeneous error types of the two underlying transports.
+#[derive(Debug, Clone)]
+pub enum EitherError<A, B> {
+	First(A),
+	Second(B),
+}
+
+impl<A, B> fmt::Display for EitherError<A, B>
+	where A: fmt::Display,
+		  B: fmt::Display
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			EitherError::First(ref err) => write!(f, "{}", err),
+			EitherError::Second(ref err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl<A, B> error::Error for EitherError<A, B>
+	where A: error::Error,
+		  B: error::Error
+{
+}
+
+/// Error produced by the `Transport` built through `with_upgrade`.
+///
+/// The protocol negotiation and the upgrade itself happen asynchronously inside the returned
+/// listener stream and dial future (where they surface as `io::Error`), so the only error that can
+/// be reported synchronously by `listen_on`/`dial` is the one of the underlying transport.
+#[derive(Debug)]
+pub enum TransportUpgradeError<TTransErr> {
+	/// Error in the underlying transport.
+	Transport(TTransErr),
+}
+
+impl<TTransErr> fmt::Display for TransportUpgradeError<TTransErr>
+	where TTransErr: fmt::Display
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			TransportUpgradeError::Transport(ref err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl<TTransErr> error::Error for TransportUpgradeError<TTransErr>
+	where TTransErr: error::Error
+{
 }
 
 /// Dummy implementation of `Transport` that just denies every single attempt.
@@ -106,15 +276,18 @@ impl Transport for DeniedTransport {
 	type RawConn = Cursor<Vec<u8>>;
 	type Listener = Box<Stream<Item = Self::RawConn, Error = IoError>>;
 	type Dial = Box<Future<Item = Self::RawConn, Error = IoError>>;
+	type Error = IoError;
 
 	#[inline]
-	fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, (Self, Multiaddr)> {
-		Err((DeniedTransport, addr))
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		Err(TransportError::MultiaddrNotSupported(addr))
 	}
 
 	#[inline]
-	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, (Self, Multiaddr)> {
-		Err((DeniedTransport, addr))
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		Err(TransportError::MultiaddrNotSupported(addr))
 	}
 }
 
@@ -132,30 +305,56 @@ impl<A, B> Transport for OrTransport<A, B>
 		<A::Dial as IntoFuture>::Future,
 		<B::Dial as IntoFuture>::Future,
 	>;
+	type Error = EitherError<A::Error, B::Error>;
 
-	fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, (Self, Multiaddr)> {
-		let (first, addr) = match self.0.listen_on(addr) {
-			Ok(connec) => return Ok(EitherStream::First(connec)),
-			Err(err) => err,
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		let addr = match self.0.listen_on(addr) {
+			Ok((connec, addr)) => return Ok((EitherStream::First(connec), addr)),
+			Err(TransportError::MultiaddrNotSupported(addr)) => addr,
+			Err(TransportError::Other(err)) => {
+				return Err(TransportError::Other(EitherError::First(err)))
+			}
 		};
 
 		match self.1.listen_on(addr) {
-			Ok(connec) => Ok(EitherStream::Second(connec)),
-			Err((second, addr)) => Err((OrTransport(first, second), addr)),
+			Ok((connec, addr)) => Ok((EitherStream::Second(connec), addr)),
+			Err(TransportError::MultiaddrNotSupported(addr)) => {
+				Err(TransportError::MultiaddrNotSupported(addr))
+			}
+			Err(TransportError::Other(err)) => {
+				Err(TransportError::Other(EitherError::Second(err)))
+			}
 		}
 	}
 
-	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, (Self, Multiaddr)> {
-		let (first, addr) = match self.0.dial(addr) {
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let addr = match self.0.dial(addr) {
 			Ok(connec) => return Ok(EitherTransportFuture::First(connec.into_future())),
-			Err(err) => err,
+			Err(TransportError::MultiaddrNotSupported(addr)) => addr,
+			Err(TransportError::Other(err)) => {
+				return Err(TransportError::Other(EitherError::First(err)))
+			}
 		};
 
 		match self.1.dial(addr) {
 			Ok(connec) => Ok(EitherTransportFuture::Second(connec.into_future())),
-			Err((second, addr)) => Err((OrTransport(first, second), addr)),
+			Err(TransportError::MultiaddrNotSupported(addr)) => {
+				Err(TransportError::MultiaddrNotSupported(addr))
+			}
+			Err(TransportError::Other(err)) => {
+				Err(TransportError::Other(EitherError::Second(err)))
+			}
 		}
 	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.0
+			.nat_traversal(server, observed)
+			.or_else(|| self.1.nat_traversal(server, observed))
+	}
 }
 
 /// Implements `Stream` and dispatches all method calls to either `First` or `Second`.
@@ -287,6 +486,661 @@ impl<A, B> Write for EitherSocket<A, B>
 	}
 }
 
+/// See `Transport::map`.
+#[derive(Debug, Copy, Clone)]
+pub struct Map<T, F> {
+	transport: T,
+	map: F,
+}
+
+impl<T, F, O> Transport for Map<T, F>
+	where T: Transport,
+		  F: FnOnce(T::RawConn) -> O + Clone,
+		  O: AsyncRead + AsyncWrite
+{
+	type RawConn = O;
+	type Listener = MapStream<T::Listener, F>;
+	type Dial = MapFuture<<T::Dial as IntoFuture>::Future, F>;
+	type Error = T::Error;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		let map = self.map;
+		self.transport
+			.listen_on(addr)
+			.map(move |(stream, addr)| (MapStream { stream: stream, map: map }, addr))
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let map = self.map;
+		self.transport
+			.dial(addr)
+			.map(move |future| MapFuture { future: future.into_future(), map: Some(map) })
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.transport.nat_traversal(server, observed)
+	}
+}
+
+/// Applies a function to the item produced by a `Dial` future. See `Transport::map`.
+pub struct MapFuture<T, F> {
+	future: T,
+	map: Option<F>,
+}
+
+impl<T, F, O> Future for MapFuture<T, F>
+	where T: Future<Error = IoError>,
+		  F: FnOnce(T::Item) -> O
+{
+	type Item = O;
+	type Error = IoError;
+
+	#[inline]
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let item = try_ready!(self.future.poll());
+		let map = self.map.take().expect("MapFuture polled after completion");
+		Ok(Async::Ready(map(item)))
+	}
+}
+
+/// Applies a function to every item produced by a `Listener` stream. See `Transport::map`.
+pub struct MapStream<T, F> {
+	stream: T,
+	map: F,
+}
+
+impl<T, F, O> Stream for MapStream<T, F>
+	where T: Stream<Error = IoError>,
+		  F: FnOnce(T::Item) -> O + Clone
+{
+	type Item = O;
+	type Error = IoError;
+
+	#[inline]
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		match try_ready!(self.stream.poll()) {
+			Some(item) => {
+				let map = self.map.clone();
+				Ok(Async::Ready(Some(map(item))))
+			}
+			None => Ok(Async::Ready(None)),
+		}
+	}
+}
+
+/// See `Transport::map_err`.
+#[derive(Debug, Copy, Clone)]
+pub struct MapErr<T, F> {
+	transport: T,
+	map_err: F,
+}
+
+impl<T, F, E> Transport for MapErr<T, F>
+	where T: Transport,
+		  F: FnOnce(T::Error) -> E + Clone,
+		  E: error::Error
+{
+	type RawConn = T::RawConn;
+	type Listener = T::Listener;
+	type Dial = T::Dial;
+	type Error = E;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		let map_err = self.map_err;
+		self.transport.listen_on(addr).map_err(move |err| match err {
+			TransportError::MultiaddrNotSupported(addr) => TransportError::MultiaddrNotSupported(addr),
+			TransportError::Other(err) => TransportError::Other(map_err(err)),
+		})
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let map_err = self.map_err;
+		self.transport.dial(addr).map_err(move |err| match err {
+			TransportError::MultiaddrNotSupported(addr) => TransportError::MultiaddrNotSupported(addr),
+			TransportError::Other(err) => TransportError::Other(map_err(err)),
+		})
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.transport.nat_traversal(server, observed)
+	}
+}
+
+/// See `Transport::and_then`.
+#[derive(Debug, Copy, Clone)]
+pub struct AndThen<T, C> {
+	transport: T,
+	fun: C,
+}
+
+impl<T, C, F, O> Transport for AndThen<T, C>
+	where T: Transport,
+		  C: FnOnce(T::RawConn) -> F + Clone,
+		  F: IntoFuture<Item = O, Error = IoError>,
+		  O: AsyncRead + AsyncWrite
+{
+	type RawConn = O;
+	type Listener = AndThenStream<T::Listener, C, F::Future>;
+	type Dial = AndThenFuture<<T::Dial as IntoFuture>::Future, C, F::Future>;
+	type Error = T::Error;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		let fun = self.fun;
+		self.transport
+			.listen_on(addr)
+			.map(move |(stream, addr)| {
+				(AndThenStream { stream: stream, fun: fun, pending: None }, addr)
+			})
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let fun = self.fun;
+		self.transport
+			.dial(addr)
+			.map(move |future| AndThenFuture::First(future.into_future(), Some(fun)))
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.transport.nat_traversal(server, observed)
+	}
+}
+
+/// Runs an asynchronous step on the item produced by a `Dial` future. See `Transport::and_then`.
+pub enum AndThenFuture<T, C, F> {
+	First(T, Option<C>),
+	Second(F),
+}
+
+impl<T, C, U> Future for AndThenFuture<T, C, U::Future>
+	where T: Future<Error = IoError>,
+		  C: FnOnce(T::Item) -> U,
+		  U: IntoFuture<Error = IoError>
+{
+	type Item = U::Item;
+	type Error = IoError;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let future = match *self {
+				AndThenFuture::First(ref mut future, ref mut fun) => {
+					let item = try_ready!(future.poll());
+					let fun = fun.take().expect("AndThenFuture polled after completion");
+					fun(item).into_future()
+				}
+				AndThenFuture::Second(ref mut future) => return future.poll(),
+			};
+
+			*self = AndThenFuture::Second(future);
+		}
+	}
+}
+
+/// Runs an asynchronous step on every item produced by a `Listener` stream. See
+/// `Transport::and_then`.
+pub struct AndThenStream<T, C, F> {
+	stream: T,
+	fun: C,
+	pending: Option<F>,
+}
+
+impl<T, C, U> Stream for AndThenStream<T, C, U::Future>
+	where T: Stream<Error = IoError>,
+		  C: FnOnce(T::Item) -> U + Clone,
+		  U: IntoFuture<Error = IoError>
+{
+	type Item = U::Item;
+	type Error = IoError;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		loop {
+			let item = match self.pending {
+				Some(ref mut future) => Some(try_ready!(future.poll())),
+				None => None,
+			};
+
+			if let Some(item) = item {
+				self.pending = None;
+				return Ok(Async::Ready(Some(item)));
+			}
+
+			match try_ready!(self.stream.poll()) {
+				Some(connection) => {
+					let fun = self.fun.clone();
+					self.pending = Some(fun(connection).into_future());
+				}
+				None => return Ok(Async::Ready(None)),
+			}
+		}
+	}
+}
+
+/// Boxed listener stream returned by a `Boxed` transport.
+type BoxedListener<C> = Box<Stream<Item = C, Error = IoError> + Send>;
+/// Boxed dial future returned by a `Boxed` transport.
+type BoxedDial<C> = Box<Future<Item = C, Error = IoError> + Send>;
+
+/// See `Transport::boxed`.
+pub struct Boxed<C, E> {
+	inner: Arc<Abstract<C, E> + Send + Sync>,
+}
+
+/// Object-safe counterpart of `Transport` used internally by `Boxed`.
+trait Abstract<C, E> {
+	fn listen_on(&self, addr: Multiaddr)
+		-> Result<(BoxedListener<C>, Multiaddr), TransportError<E>>;
+	fn dial(&self, addr: Multiaddr) -> Result<BoxedDial<C>, TransportError<E>>;
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr>;
+}
+
+impl<T, C, E> Abstract<C, E> for T
+	where T: Transport<RawConn = C, Error = E> + Clone + 'static,
+		  T::Listener: Send + 'static,
+		  T::Dial: 'static,
+		  <T::Dial as IntoFuture>::Future: Send + 'static
+{
+	#[inline]
+	fn listen_on(&self, addr: Multiaddr)
+		-> Result<(BoxedListener<C>, Multiaddr), TransportError<E>>
+	{
+		let (listener, addr) = Clone::clone(self).listen_on(addr)?;
+		Ok((Box::new(listener), addr))
+	}
+
+	#[inline]
+	fn dial(&self, addr: Multiaddr) -> Result<BoxedDial<C>, TransportError<E>> {
+		let dial = Clone::clone(self).dial(addr)?;
+		Ok(Box::new(dial.into_future()))
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		Transport::nat_traversal(self, server, observed)
+	}
+}
+
+impl<C, E> Clone for Boxed<C, E> {
+	#[inline]
+	fn clone(&self) -> Self {
+		Boxed {
+			inner: self.inner.clone(),
+		}
+	}
+}
+
+impl<C, E> Transport for Boxed<C, E>
+	where C: AsyncRead + AsyncWrite,
+		  E: error::Error
+{
+	type RawConn = C;
+	type Listener = BoxedListener<C>;
+	type Dial = BoxedDial<C>;
+	type Error = E;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		self.inner.listen_on(addr)
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		self.inner.dial(addr)
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
+
+/// A connection whose remote `PeerId` can be retrieved by running the identify protocol over a
+/// freshly opened substream.
+///
+/// This is implemented by a stream muxer combined with the identify protocol. It is expressed as
+/// a trait here so that `IdentifyTransport` does not have to name the concrete muxer and identify
+/// types, which live in their own crates.
+pub trait IdentifyMuxer: AsyncRead + AsyncWrite + Sized {
+	/// Type that identifies the remote.
+	type PeerId;
+	/// Future returned by `identify_remote`.
+	type Identify: Future<Item = (Self::PeerId, Self), Error = IoError>;
+
+	/// Opens a dedicated substream, runs the identify exchange over it and resolves to the
+	/// remote's self-reported `PeerId` together with the muxer itself.
+	fn identify_remote(self) -> Self::Identify;
+}
+
+/// Output of an `IdentifyTransport`: a muxed connection whose remote `PeerId` has been retrieved.
+///
+/// Forwards `AsyncRead`/`AsyncWrite` to the underlying muxer, while also exposing the remote's
+/// peer id.
+///
+/// > **Note**: When no security layer (such as *secio*) authenticated the remote, the `peer_id`
+/// >           is merely *self-reported* by the other side and must **not** be trusted.
+pub struct IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	/// The remote's self-reported (and, without a security layer, untrusted) peer id.
+	pub peer_id: M::PeerId,
+	/// The underlying muxer.
+	muxer: M,
+}
+
+impl<M> IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	/// Returns a reference to the underlying muxer.
+	#[inline]
+	pub fn muxer(&self) -> &M {
+		&self.muxer
+	}
+
+	/// Consumes the output and returns the owned muxer, so it can be handed to the next layer.
+	#[inline]
+	pub fn into_inner(self) -> M {
+		self.muxer
+	}
+}
+
+impl<M> Read for IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	#[inline]
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+		self.muxer.read(buf)
+	}
+}
+
+impl<M> AsyncRead for IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	#[inline]
+	unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+		self.muxer.prepare_uninitialized_buffer(buf)
+	}
+}
+
+impl<M> Write for IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	#[inline]
+	fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+		self.muxer.write(buf)
+	}
+
+	#[inline]
+	fn flush(&mut self) -> Result<(), IoError> {
+		self.muxer.flush()
+	}
+}
+
+impl<M> AsyncWrite for IdentifyOutput<M>
+	where M: IdentifyMuxer
+{
+	#[inline]
+	fn shutdown(&mut self) -> Poll<(), IoError> {
+		self.muxer.shutdown()
+	}
+}
+
+/// Future that drives `IdentifyMuxer::identify_remote` and wraps its result in an `IdentifyOutput`.
+pub struct IdentifyFuture<M>
+	where M: IdentifyMuxer
+{
+	inner: M::Identify,
+}
+
+impl<M> Future for IdentifyFuture<M>
+	where M: IdentifyMuxer
+{
+	type Item = IdentifyOutput<M>;
+	type Error = IoError;
+
+	#[inline]
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		let (peer_id, muxer) = try_ready!(self.inner.poll());
+		Ok(Async::Ready(IdentifyOutput { peer_id: peer_id, muxer: muxer }))
+	}
+}
+
+#[inline]
+fn identify_remote<M>(muxer: M) -> IdentifyFuture<M>
+	where M: IdentifyMuxer
+{
+	IdentifyFuture { inner: muxer.identify_remote() }
+}
+
+/// Wraps around a transport whose connections are stream muxers and, immediately after a
+/// connection is established (dialing or listening), opens a dedicated substream and runs the
+/// identify protocol over it to fetch the remote's `PeerId`.
+///
+/// This is what makes the security/encryption layer optional: when no `secio`-style upgrade
+/// authenticates the peer, running identify is the only way to obtain a `PeerId`. The resulting id
+/// is therefore self-reported and untrusted (see `IdentifyOutput`).
+#[derive(Debug, Copy, Clone)]
+pub struct IdentifyTransport<TTrans> {
+	transport: TTrans,
+}
+
+impl<TTrans> IdentifyTransport<TTrans> {
+	/// Wraps `transport` so that the remote's `PeerId` is retrieved for every connection.
+	#[inline]
+	pub fn new(transport: TTrans) -> Self {
+		IdentifyTransport { transport: transport }
+	}
+}
+
+impl<TTrans, M> Transport for IdentifyTransport<TTrans>
+	where TTrans: Transport<RawConn = M>,
+		  M: IdentifyMuxer
+{
+	type RawConn = IdentifyOutput<M>;
+	type Listener = AndThenStream<TTrans::Listener, fn(M) -> IdentifyFuture<M>, IdentifyFuture<M>>;
+	type Dial = AndThenFuture<<TTrans::Dial as IntoFuture>::Future, fn(M) -> IdentifyFuture<M>, IdentifyFuture<M>>;
+	type Error = TTrans::Error;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		self.transport.listen_on(addr).map(move |(stream, addr)| {
+			let stream = AndThenStream {
+				stream: stream,
+				fun: identify_remote as fn(M) -> IdentifyFuture<M>,
+				pending: None,
+			};
+			(stream, addr)
+		})
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		self.transport.dial(addr).map(move |future| {
+			AndThenFuture::First(
+				future.into_future(),
+				Some(identify_remote as fn(M) -> IdentifyFuture<M>),
+			)
+		})
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.transport.nat_traversal(server, observed)
+	}
+}
+
+/// Describes an upgrade that may or may not be applied to a connection, decided at runtime on a
+/// per-connection basis.
+///
+/// The generic `C` is the type of the connection before it is (maybe) upgraded.
+pub trait UpgradeMaybe<C> {
+	/// Type of the connection once it has been upgraded.
+	type Upgraded;
+	/// Future returned by `try_upgrade`.
+	///
+	/// Resolves to `Ok` with the upgraded connection, or to `Err` with the original connection
+	/// handed back untouched.
+	type Future: Future<Item = Result<Self::Upgraded, C>, Error = IoError>;
+
+	/// Inspects a freshly negotiated connection (for example by sniffing the first bytes or an
+	/// optional multistream-select offer) and either upgrades it or hands back the original
+	/// socket.
+	fn try_upgrade(self, connection: C) -> Self::Future;
+}
+
+/// Transport wrapper that decides, per connection, whether to apply `U` or to pass the raw
+/// connection through. Yields an `EitherSocket<Raw, Upgraded>`.
+///
+/// This lets a node accept both plaintext and encrypted peers on the same listener, for example
+/// during a protocol migration.
+#[derive(Debug, Copy, Clone)]
+pub struct MaybeUpgrade<TInner, U> {
+	inner: TInner,
+	upgrade: U,
+}
+
+impl<TInner, U> MaybeUpgrade<TInner, U> {
+	/// Wraps `inner` so that `upgrade` is conditionally applied to each connection.
+	#[inline]
+	pub fn new(inner: TInner, upgrade: U) -> Self {
+		MaybeUpgrade { inner: inner, upgrade: upgrade }
+	}
+}
+
+impl<TInner, U, M> Transport for MaybeUpgrade<TInner, U>
+	where TInner: Transport<RawConn = M>,
+		  U: UpgradeMaybe<M> + Clone,
+		  U::Upgraded: AsyncRead + AsyncWrite
+{
+	type RawConn = EitherSocket<M, U::Upgraded>;
+	type Listener = MaybeUpgradeStream<TInner::Listener, U, U::Future>;
+	type Dial = MaybeUpgradeFuture<<TInner::Dial as IntoFuture>::Future, U, U::Future>;
+	type Error = TInner::Error;
+
+	#[inline]
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
+	{
+		let upgrade = self.upgrade;
+		self.inner.listen_on(addr).map(move |(stream, addr)| {
+			let stream = MaybeUpgradeStream {
+				stream: stream,
+				upgrade: upgrade,
+				pending: None,
+			};
+			(stream, addr)
+		})
+	}
+
+	#[inline]
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let upgrade = self.upgrade;
+		self.inner
+			.dial(addr)
+			.map(move |future| MaybeUpgradeFuture::First(future.into_future(), Some(upgrade)))
+	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
+
+/// Applies `UpgradeMaybe` to the item produced by a `Dial` future. See `MaybeUpgrade`.
+pub enum MaybeUpgradeFuture<T, U, F> {
+	First(T, Option<U>),
+	Second(F),
+}
+
+impl<T, U, M> Future for MaybeUpgradeFuture<T, U, U::Future>
+	where T: Future<Item = M, Error = IoError>,
+		  U: UpgradeMaybe<M>,
+		  U::Upgraded: AsyncRead + AsyncWrite,
+		  M: AsyncRead + AsyncWrite
+{
+	type Item = EitherSocket<M, U::Upgraded>;
+	type Error = IoError;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let future = match *self {
+				MaybeUpgradeFuture::First(ref mut future, ref mut upgrade) => {
+					let connection = try_ready!(future.poll());
+					let upgrade = upgrade.take().expect("MaybeUpgradeFuture polled after completion");
+					upgrade.try_upgrade(connection)
+				}
+				MaybeUpgradeFuture::Second(ref mut future) => {
+					let result = try_ready!(future.poll());
+					return Ok(Async::Ready(match result {
+						Ok(upgraded) => EitherSocket::Second(upgraded),
+						Err(raw) => EitherSocket::First(raw),
+					}));
+				}
+			};
+
+			*self = MaybeUpgradeFuture::Second(future);
+		}
+	}
+}
+
+/// Applies `UpgradeMaybe` to every item produced by a `Listener` stream. See `MaybeUpgrade`.
+pub struct MaybeUpgradeStream<T, U, F> {
+	stream: T,
+	upgrade: U,
+	pending: Option<F>,
+}
+
+impl<T, U, M> Stream for MaybeUpgradeStream<T, U, U::Future>
+	where T: Stream<Item = M, Error = IoError>,
+		  U: UpgradeMaybe<M> + Clone,
+		  U::Upgraded: AsyncRead + AsyncWrite,
+		  M: AsyncRead + AsyncWrite
+{
+	type Item = EitherSocket<M, U::Upgraded>;
+	type Error = IoError;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		loop {
+			let result = match self.pending {
+				Some(ref mut future) => Some(try_ready!(future.poll())),
+				None => None,
+			};
+
+			if let Some(result) = result {
+				self.pending = None;
+				return Ok(Async::Ready(Some(match result {
+					Ok(upgraded) => EitherSocket::Second(upgraded),
+					Err(raw) => EitherSocket::First(raw),
+				})));
+			}
+
+			match try_ready!(self.stream.poll()) {
+				Some(connection) => {
+					let upgrade = self.upgrade.clone();
+					self.pending = Some(upgrade.try_upgrade(connection));
+				}
+				None => return Ok(Async::Ready(None)),
+			}
+		}
+	}
+}
+
 /// Implemented on structs that describe a possible upgrade to a connection between two peers.
 ///
 /// The generic `C` is the type of the incoming connection before it is upgraded.
@@ -312,8 +1166,10 @@ pub trait ConnectionUpgrade<C: AsyncRead + AsyncWrite> {
 	/// > **Note**: For upgrades that add an intermediary layer (such as `secio` or `multiplex`),
 	/// >           this associated type must implement `AsyncRead + AsyncWrite`.
 	type Output;
+	/// Error that can happen while upgrading the connection.
+	type Error: error::Error;
 	/// Type of the future that will resolve to `Self::Output`.
-	type Future: Future<Item = Self::Output, Error = IoError>;
+	type Future: Future<Item = Self::Output, Error = Self::Error>;
 
 	/// This method is called after protocol negotiation has been performed.
 	///
@@ -343,6 +1199,7 @@ impl<C, A, B> ConnectionUpgrade<C> for OrUpgrade<A, B>
 	}
 
 	type Output = EitherSocket<A::Output, B::Output>;
+	type Error = EitherError<A::Error, B::Error>;
 	type Future = EitherConnUpgrFuture<A::Future, B::Future>;
 
 	#[inline]
@@ -378,21 +1235,21 @@ pub enum EitherConnUpgrFuture<A, B> {
 }
 
 impl<A, B> Future for EitherConnUpgrFuture<A, B>
-	where A: Future<Error = IoError>,
-		  B: Future<Error = IoError>
+	where A: Future,
+		  B: Future
 {
 	type Item = EitherSocket<A::Item, B::Item>;
-	type Error = IoError;
+	type Error = EitherError<A::Error, B::Error>;
 
 	#[inline]
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
 		match self {
 			&mut EitherConnUpgrFuture::First(ref mut a) => {
-				let item = try_ready!(a.poll());
+				let item = try_ready!(a.poll().map_err(EitherError::First));
 				Ok(Async::Ready(EitherSocket::First(item)))
 			}
 			&mut EitherConnUpgrFuture::Second(ref mut b) => {
-				let item = try_ready!(b.poll());
+				let item = try_ready!(b.poll().map_err(EitherError::Second));
 				Ok(Async::Ready(EitherSocket::Second(item)))
 			}
 		}
@@ -450,6 +1307,7 @@ impl<C> ConnectionUpgrade<C> for PlainText
 	where C: AsyncRead + AsyncWrite
 {
 	type Output = C;
+	type Error = IoError;
 	type Future = FutureResult<C, IoError>;
 	type UpgradeIdentifier = ();
 	type NamesIter = iter::Once<(Bytes, ())>;
@@ -477,7 +1335,8 @@ pub struct UpgradedNode<T, C> {
 
 impl<'a, T, C> UpgradedNode<T, C>
 	where T: Transport + 'a,
-		  C: ConnectionUpgrade<T::RawConn> + 'a
+		  C: ConnectionUpgrade<T::RawConn> + 'a,
+		  C::Error: Send + Sync + 'static
 {
 	/// Builds a new struct that implements `ConnectionUpgrade` that contains both `self` and
 	/// `other_upg`.
@@ -503,18 +1362,16 @@ impl<'a, T, C> UpgradedNode<T, C>
 	pub fn dial(
 		self,
 		addr: Multiaddr,
-	) -> Result<Box<Future<Item = C::Output, Error = IoError> + 'a>, (Self, Multiaddr)> {
+	) -> Result<Box<Future<Item = C::Output, Error = IoError> + 'a>, TransportError<TransportUpgradeError<T::Error>>> {
 		let upgrade = self.upgrade;
 
 		let dialed_fut = match self.transports.dial(addr) {
 			Ok(f) => f.into_future(),
-			Err((trans, addr)) => {
-				let builder = UpgradedNode {
-					transports: trans,
-					upgrade: upgrade,
-				};
-
-				return Err((builder, addr));
+			Err(TransportError::MultiaddrNotSupported(addr)) => {
+				return Err(TransportError::MultiaddrNotSupported(addr));
+			}
+			Err(TransportError::Other(err)) => {
+				return Err(TransportError::Other(TransportUpgradeError::Transport(err)));
 			}
 		};
 
@@ -524,11 +1381,12 @@ impl<'a, T, C> UpgradedNode<T, C>
 				let iter = upgrade.protocol_names()
 					.map(|(name, id)| (name, <Bytes as PartialEq>::eq, id));
 				let negotiated = multistream_select::dialer_select_proto(connection, iter)
-					.map_err(|err| panic!("{:?}", err));      // TODO:
+					.map_err(|err| IoError::new(IoErrorKind::Other, err));
 				negotiated.map(|(upgrade_id, conn)| (upgrade_id, conn, upgrade))
 			})
 			.and_then(|(upgrade_id, connection, upgrade)| {
 				upgrade.upgrade(connection, upgrade_id)
+					.map_err(|err| IoError::new(IoErrorKind::Other, err))
 			});
 
 		Ok(Box::new(future))
@@ -543,21 +1401,19 @@ impl<'a, T, C> UpgradedNode<T, C>
 	pub fn listen_on(
 		self,
 		addr: Multiaddr,
-	) -> Result<Box<Stream<Item = C::Output, Error = IoError> + 'a>, (Self, Multiaddr)>
+	) -> Result<(Box<Stream<Item = C::Output, Error = IoError> + 'a>, Multiaddr), TransportError<TransportUpgradeError<T::Error>>>
 		where C::NamesIter: Clone, // TODO: not elegant
 			  C: Clone
 	{
 		let upgrade = self.upgrade;
 
-		let listening_stream = match self.transports.listen_on(addr) {
-			Ok(l) => l,
-			Err((trans, addr)) => {
-				let builder = UpgradedNode {
-					transports: trans,
-					upgrade: upgrade,
-				};
-
-				return Err((builder, addr));
+		let (listening_stream, bound_addr) = match self.transports.listen_on(addr) {
+			Ok((l, bound_addr)) => (l, bound_addr),
+			Err(TransportError::MultiaddrNotSupported(addr)) => {
+				return Err(TransportError::MultiaddrNotSupported(addr));
+			}
+			Err(TransportError::Other(err)) => {
+				return Err(TransportError::Other(TransportUpgradeError::Transport(err)));
 			}
 		};
 
@@ -571,16 +1427,15 @@ impl<'a, T, C> UpgradedNode<T, C>
 				}
 				let iter = upgrade.protocol_names().map(iter_map);
 				let negotiated = multistream_select::listener_select_proto(connection, iter)
-					.map_err(|err| panic!("{:?}", err));      // TODO:
+					.map_err(|err| IoError::new(IoErrorKind::Other, err));
 				negotiated.map(move |(upgrade_id, conn)| (upgrade_id, conn, upgrade))
-					.map_err(|_| panic!())    // TODO:
 			})
-			.map_err(|_| panic!())      // TODO:
 			.and_then(|(upgrade_id, connection, upgrade)| {
 				upgrade.upgrade(connection, upgrade_id)
+					.map_err(|err| IoError::new(IoErrorKind::Other, err))
 			});
 
-		Ok(Box::new(stream))
+		Ok((Box::new(stream), bound_addr))
 	}
 }
 
@@ -588,24 +1443,32 @@ impl<T, C> Transport for UpgradedNode<T, C>
 	where T: Transport + 'static,
 		  C: ConnectionUpgrade<T::RawConn> + 'static,
 		  C::Output: AsyncRead + AsyncWrite,
+		  C::Error: Send + Sync + 'static,
 		  C::NamesIter: Clone, // TODO: not elegant
 		  C: Clone
 {
 	type RawConn = C::Output;
 	type Listener = Box<Stream<Item = C::Output, Error = IoError>>;
 	type Dial = Box<Future<Item = C::Output, Error = IoError>>;
+	type Error = TransportUpgradeError<T::Error>;
 
 	#[inline]
-	fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, (Self, Multiaddr)>
+	fn listen_on(self, addr: Multiaddr)
+		-> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>>
 		where Self: Sized
 	{
 		self.listen_on(addr)
 	}
 
 	#[inline]
-	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, (Self, Multiaddr)>
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>>
 		where Self: Sized
 	{
 		self.dial(addr)
 	}
+
+	#[inline]
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.transports.nat_traversal(server, observed)
+	}
 }